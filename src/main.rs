@@ -1,11 +1,12 @@
 use std::convert::Infallible;
+use std::fs::File;
 use std::io::prelude::*;
-use std::io::{self, BufRead, Error, ErrorKind};
+use std::io::{self, BufRead, BufReader, Error, ErrorKind};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
-use tokio::sync::mpsc;
 use warp::Filter;
 
 mod markov;
@@ -32,20 +33,49 @@ struct Config {
     port: Option<u16>,
 
     /// File to process
-    #[structopt(name = "INPUT", parse(from_os_str))]
-    input: PathBuf,
+    #[structopt(name = "INPUT", parse(from_os_str), required_unless = "model")]
+    input: Option<PathBuf>,
+
+    /// Load a prebuilt model instead of indexing INPUT
+    #[structopt(long, parse(from_os_str))]
+    model: Option<PathBuf>,
+
+    /// Save the trained model to this path after indexing
+    #[structopt(long, parse(from_os_str))]
+    save: Option<PathBuf>,
+
+    /// N-gram order (prefix length) to train with
+    #[structopt(long, default_value = "2")]
+    order: usize,
 }
 
-fn setup_index(config: &Config) -> markov::Chain {
-    let mut index = markov::Chain::new();
-    index.feed_file(&config.input).unwrap();
-    index
+fn setup_index(config: &Config) -> io::Result<markov::Chain> {
+    if let Some(model_path) = &config.model {
+        let mut reader = BufReader::new(File::open(model_path)?);
+        return markov::Chain::load(&mut reader);
+    }
+
+    let input = config
+        .input
+        .as_ref()
+        .expect("INPUT is required when --model is not given");
+    let mut index = markov::Chain::with_order(config.order);
+    index.feed_file(input)?;
+    Ok(index)
+}
+
+fn default_temperature() -> f32 {
+    1.0
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct MarkovRequest {
     seed: Option<String>,
     target: Option<i32>,
+    #[serde(default = "default_temperature")]
+    temperature: f32,
+    #[serde(default)]
+    top_k: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -53,23 +83,33 @@ struct MarkovResponse {
     response: Option<String>,
 }
 
-type MarkovRequestMessage = (MarkovRequest, mpsc::Sender<MarkovResponse>);
+#[derive(Clone)]
+struct State {
+    index: Arc<markov::Chain>,
+    debug: bool,
+}
 
-async fn respond(
-    req: MarkovRequest,
-    mut tx_req: mpsc::Sender<MarkovRequestMessage>,
-) -> Result<MarkovResponse, Infallible> {
-    let (tx_resp, mut rx_resp) = mpsc::channel::<MarkovResponse>(1);
-    tx_req.send((req, tx_resp)).await.expect("Oh noes");
-    let resp = rx_resp.recv().await;
-    Ok(resp.unwrap())
+async fn respond(req: MarkovRequest, state: State) -> Result<MarkovResponse, Infallible> {
+    if state.debug {
+        println!("Processing request: {:?}", req);
+    }
+    let target = req.target.unwrap_or(20);
+    let params = markov::SamplingParams {
+        temperature: req.temperature,
+        top_k: req.top_k,
+    };
+    let response = match req.seed {
+        None => state.index.generate_best(target, params),
+        Some(seed) => state.index.generate_best_from(seed, target, params),
+    };
+    Ok(MarkovResponse { response })
 }
 
 async fn to_json(resp: MarkovResponse) -> Result<impl warp::Reply, Infallible> {
     Ok(warp::reply::json(&resp))
 }
 
-async fn repl(tx_req: mpsc::Sender<MarkovRequestMessage>) {
+async fn repl(state: State) {
     loop {
         let res = read_line("seed> ");
         match res {
@@ -82,8 +122,10 @@ async fn repl(tx_req: mpsc::Sender<MarkovRequestMessage>) {
                 let input = MarkovRequest {
                     seed: seedlet,
                     target: None,
+                    temperature: default_temperature(),
+                    top_k: None,
                 };
-                if let Ok(resp) = respond(input, tx_req.clone()).await {
+                if let Ok(resp) = respond(input, state.clone()).await {
                     if let Some(gen) = resp.response {
                         println!("\n{}\n", gen);
                     }
@@ -102,50 +144,61 @@ async fn main() {
     let config = Config::from_args();
     if config.debug {
         println!("Config: {:?}", config);
-        println!("Indexing {}...", config.input.display());
+        match (&config.model, &config.input) {
+            (Some(path), _) => println!("Loading model {}...", path.display()),
+            (None, Some(path)) => println!("Indexing {}...", path.display()),
+            (None, None) => unreachable!("structopt enforces INPUT or --model"),
+        }
     }
 
-    let mut index = setup_index(&config);
+    let index = Arc::new(setup_index(&config).unwrap());
 
     if config.debug {
         index.printsizes();
     }
-    let debug = config.debug;
-
-    let (tx_req, mut rx_req) = mpsc::channel::<MarkovRequestMessage>(100);
 
-    let _responder = tokio::spawn(async move {
-        while let Some(work) = rx_req.recv().await {
-            let (req, mut tx_resp): MarkovRequestMessage = work;
-            let target = req.target.unwrap_or(20);
-            if debug {
-                println!("Processing request: {:?}", req);
-            }
-            let response = match req.seed {
-                None => index.generate_best(target),
-                Some(seed) => index.generate_best_from(seed, target),
-            };
-            tx_resp
-                .send(MarkovResponse { response })
-                .await
-                .expect("what?");
+    if let Some(save_path) = &config.save {
+        if config.debug {
+            println!("Saving model to {}...", save_path.display());
         }
-    });
+        let mut file = File::create(save_path).unwrap();
+        index.save(&mut file).unwrap();
+    }
+
+    let state = State {
+        index,
+        debug: config.debug,
+    };
 
     if let Some(port) = config.port {
         // POST / {"seed": "Sean", "target": 20}
         let endpoint = warp::post()
             .and(warp::body::json())
-            .and(warp::any().map(move || tx_req.clone()))
+            .and(warp::any().map({
+                let state = state.clone();
+                move || state.clone()
+            }))
             .and_then(respond)
             .and_then(to_json);
 
+        let mut dot_bytes = Vec::new();
+        state
+            .index
+            .to_dot(&mut dot_bytes, &markov::DotOptions::default())
+            .unwrap();
+        let dot_output = String::from_utf8(dot_bytes).unwrap();
+
+        // GET /graph
+        let graph = warp::path("graph").and(warp::get()).map(move || {
+            warp::reply::with_header(dot_output.clone(), "content-type", "text/vnd.graphviz")
+        });
+
         if config.debug {
             println!("Binding server on port {}", port);
         }
 
-        warp::serve(endpoint).run(([127, 0, 0, 1], port)).await;
+        warp::serve(endpoint.or(graph)).run(([127, 0, 0, 1], port)).await;
     } else {
-        repl(tx_req).await;
+        repl(state).await;
     }
 }
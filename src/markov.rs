@@ -1,6 +1,6 @@
+use std::sync::OnceLock;
 use std::collections::HashMap;
 use std::fs::File;
-use std::hash::Hash;
 use std::io::prelude::*;
 use std::io::{self, BufReader};
 use std::path::Path;
@@ -8,20 +8,152 @@ use std::vec::Vec;
 
 use rand::rngs::ThreadRng;
 use rand::seq::SliceRandom;
-use rand::{thread_rng, Rng};
+use rand::thread_rng;
 
 use indexmap::IndexSet;
 
 type TokID = u32;
-type Prefix1 = TokID;
-type Prefix2 = (TokID, TokID);
+type Prefix = Vec<TokID>;
 type HashTokSet = HashMap<TokID, u16>;
 
+const MODEL_MAGIC: &[u8; 4] = b"FAKE";
+const MODEL_VERSION: u8 = 2;
+
+/// Hard cap on tokens generated in one direction. Random sampling thins out
+/// to the sentinel almost surely, but low-temperature (near-argmax)
+/// sampling can get stuck looping a dominant cycle that never reaches it.
+const MAX_GENERATED_TOKENS: usize = 1000;
+
+fn write_u16<W: Write>(w: &mut W, v: u16) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn write_u32<W: Write>(w: &mut W, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn write_bytes<W: Write>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_u32(w, bytes.len() as u32)?;
+    w.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(r)? as usize;
+    let mut buf = vec![0u8; len];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_string<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    write_bytes(w, s.as_bytes())
+}
+
+fn read_string<R: Read>(r: &mut R) -> io::Result<String> {
+    let bytes = read_bytes(r)?;
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn dot_node_id(prefix: &[TokID]) -> String {
+    let ids: Vec<String> = prefix.iter().map(TokID::to_string).collect();
+    format!("n{}", ids.join("_"))
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Options controlling how much of the transition graph `Chain::to_dot`
+/// renders.
+pub struct DotOptions {
+    /// Keep only the `top_n` heaviest outgoing edges per node.
+    pub top_n: Option<usize>,
+    /// Skip the empty-`""` sentinel token used as the entrypoint/terminator.
+    pub skip_sentinel: bool,
+}
+
+impl Default for DotOptions {
+    fn default() -> DotOptions {
+        DotOptions {
+            top_n: None,
+            skip_sentinel: true,
+        }
+    }
+}
+
+/// Controls how a `TokSet` samples its next token.
+#[derive(Debug, Clone, Copy)]
+pub struct SamplingParams {
+    /// `< 1.0` sharpens toward the most frequent successor, `> 1.0` flattens
+    /// the distribution. Values at or near `0.0` degenerate to argmax.
+    pub temperature: f32,
+    /// Keep only the `top_k` highest-weight candidates before drawing.
+    pub top_k: Option<usize>,
+}
+
+impl Default for SamplingParams {
+    fn default() -> SamplingParams {
+        SamplingParams {
+            temperature: 1.0,
+            top_k: None,
+        }
+    }
+}
+
+/// Apply temperature/top-k to `(token, count)` pairs and draw one, or `None`
+/// if there are no candidates.
+fn weighted_choice(
+    rng: &mut ThreadRng,
+    counts: Vec<(TokID, u32)>,
+    params: &SamplingParams,
+) -> Option<TokID> {
+    if counts.is_empty() {
+        return None;
+    }
+
+    if params.temperature <= f32::EPSILON {
+        return counts.into_iter().max_by_key(|(_, count)| *count).map(|(tok, _)| tok);
+    }
+
+    // Normalize against the max count before exponentiating. Raw counts
+    // raised to 1/temperature overflow to `inf` once temperature drops
+    // much below 1.0 on corpora with large counts; scaling first keeps
+    // the base in [0, 1] without changing the relative ranking.
+    let max_count = counts.iter().map(|(_, count)| *count).max().unwrap_or(1) as f32;
+    let mut weighted: Vec<(TokID, f32)> = counts
+        .into_iter()
+        .map(|(tok, count)| {
+            (tok, (count as f32 / max_count).powf(1.0 / params.temperature))
+        })
+        .collect();
+
+    weighted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    if let Some(top_k) = params.top_k {
+        weighted.truncate(top_k.max(1));
+    }
+
+    weighted
+        .choose_weighted(rng, |e| e.1)
+        .ok()
+        .map(|(tok, _)| *tok)
+}
+
 pub trait TokSet {
     fn new() -> Self;
     fn is_empty(&self) -> bool;
     fn add_entry(&mut self, entry: TokID);
-    fn choose(&self, rng: &mut ThreadRng) -> TokID;
+    fn choose_with(&self, rng: &mut ThreadRng, params: &SamplingParams) -> Option<TokID>;
 }
 
 impl TokSet for HashTokSet {
@@ -37,10 +169,9 @@ impl TokSet for HashTokSet {
         self.entry(entry).and_modify(|e| *e += 1).or_insert(1);
     }
 
-    fn choose(&self, rng: &mut ThreadRng) -> TokID {
-        let choicev: Vec<_> = self.iter().map(|(k, v)| (k, v)).collect();
-        let choice = choicev.choose_weighted(rng, |e| e.1).unwrap().0;
-        *choice
+    fn choose_with(&self, rng: &mut ThreadRng, params: &SamplingParams) -> Option<TokID> {
+        let counts: Vec<(TokID, u32)> = self.iter().map(|(k, v)| (*k, u32::from(*v))).collect();
+        weighted_choice(rng, counts, params)
     }
 }
 
@@ -49,6 +180,10 @@ struct BufferTokSet {
     buf: Vec<u8>,
     c2: u16,
     c1: u16,
+    // Lazily-tallied (token, count) pairs, populated on first `choose_with`.
+    // `buf`/`c1`/`c2` never change once a set is being sampled from, so the
+    // tally stays valid for the set's lifetime.
+    tally: OnceLock<Vec<(TokID, u32)>>,
 }
 
 impl BufferTokSet {
@@ -57,6 +192,7 @@ impl BufferTokSet {
             buf: Vec::new(),
             c2: 0,
             c1: 0,
+            tally: OnceLock::new(),
         }
     }
     fn length(&self) -> usize {
@@ -124,6 +260,33 @@ impl BufferTokSet {
             panic!("4-byte entries not supported")
         }
     }
+
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u16(w, self.c1)?;
+        write_u16(w, self.c2)?;
+        write_bytes(w, &self.buf)
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<BufferTokSet> {
+        let c1 = read_u16(r)?;
+        let c2 = read_u16(r)?;
+        let buf = read_bytes(r)?;
+
+        let prefix = c1 as usize + 2 * c2 as usize;
+        if prefix > buf.len() || (buf.len() - prefix) % 3 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "corrupt BufferTokSet: buf length inconsistent with c1/c2",
+            ));
+        }
+
+        Ok(BufferTokSet {
+            buf,
+            c2,
+            c1,
+            tally: OnceLock::new(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -171,6 +334,59 @@ mod tests {
             assert_eq!(0xFF + 1, tokset.get(i));
         }
     }
+    #[test]
+    fn write_read_round_trip() {
+        let mut tokset = BufferTokSet::new();
+        tokset.add_entry(2);
+        tokset.add_entry(300);
+        tokset.add_entry(0xFFFFF);
+        let mut buf = Vec::new();
+        tokset.write_to(&mut buf).unwrap();
+        let loaded = BufferTokSet::read_from(&mut &buf[..]).unwrap();
+        assert_eq!(tokset, loaded);
+    }
+    #[test]
+    fn read_from_rejects_corrupt_buffer() {
+        // Claims 5 single-byte entries (c1 = 5) but the buffer only holds 2.
+        let mut buf = Vec::new();
+        write_u16(&mut buf, 5).unwrap();
+        write_u16(&mut buf, 0).unwrap();
+        write_bytes(&mut buf, &[1, 2]).unwrap();
+        let err = BufferTokSet::read_from(&mut &buf[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    #[test]
+    fn chain_order_one_feed_and_generate() {
+        let mut chain = Chain::with_order(1);
+        chain.feed_str("a b a b a b");
+        let rng = &mut thread_rng();
+        let out = chain
+            .generate_one_from(rng, "a", SamplingParams::default())
+            .unwrap();
+        assert!(!out.is_empty());
+        assert!(out.iter().all(|w| w == "a" || w == "b"));
+    }
+    #[test]
+    fn chain_order_three_feed_and_generate() {
+        let mut chain = Chain::with_order(3);
+        chain.feed_str("the quick brown fox jumps over the lazy dog");
+        // A one-word seed is shorter than order 3, exercising the
+        // sentinel-padding path fixed in 76d14eb.
+        let out = chain
+            .generate_one_from(&mut thread_rng(), "fox", SamplingParams::default())
+            .unwrap();
+        assert!(out.contains(&"fox".to_string()));
+        assert!(!out.iter().any(|w| w.is_empty()));
+    }
+    #[test]
+    fn chain_save_load_round_trip() {
+        let mut chain = Chain::with_order(2);
+        chain.feed_str("the quick brown fox jumps over the lazy dog");
+        let mut buf = Vec::new();
+        chain.save(&mut buf).unwrap();
+        let loaded = Chain::load(&mut &buf[..]).unwrap();
+        assert_eq!(chain, loaded);
+    }
 }
 
 impl TokSet for BufferTokSet {
@@ -185,9 +401,15 @@ impl TokSet for BufferTokSet {
         self.add(entry);
     }
 
-    fn choose(&self, rng: &mut ThreadRng) -> TokID {
-        let n: usize = rng.gen_range(0, self.length());
-        self.get(n)
+    fn choose_with(&self, rng: &mut ThreadRng, params: &SamplingParams) -> Option<TokID> {
+        let counts = self.tally.get_or_init(|| {
+            let mut counts: HashMap<TokID, u32> = HashMap::new();
+            for i in 0..self.length() {
+                *counts.entry(self.get(i)).or_insert(0) += 1;
+            }
+            counts.into_iter().collect()
+        });
+        weighted_choice(rng, counts.clone(), params)
     }
 }
 
@@ -217,31 +439,23 @@ impl Dict {
     pub fn entry(&self, token_id: TokID) -> Option<String> {
         self.entries.get_index(token_id as usize).cloned()
     }
-}
 
-pub trait Prefix: Eq + Hash + Clone {
-    fn size() -> usize;
-    fn entrypoint(dict: &mut Dict) -> Self;
-}
-
-impl Prefix for Prefix1 {
-    fn size() -> usize {
-        1
-    }
-
-    fn entrypoint(dict: &mut Dict) -> Prefix1 {
-        dict.tokid("")
-    }
-}
-
-impl Prefix for Prefix2 {
-    fn size() -> usize {
-        2
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u32(w, self.entries.len() as u32)?;
+        for token in &self.entries {
+            write_string(w, token)?;
+        }
+        Ok(())
     }
 
-    fn entrypoint(dict: &mut Dict) -> Prefix2 {
-        let none = dict.tokid("");
-        (none, none)
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Dict> {
+        let count = read_u32(r)?;
+        let mut entries = IndexSet::with_capacity(count as usize);
+        for _ in 0..count {
+            // Insertion order must match the file to keep TokIDs stable.
+            entries.insert(read_string(r)?);
+        }
+        Ok(Dict { entries })
     }
 }
 
@@ -250,7 +464,7 @@ pub enum Direction {
     Reverse,
 }
 
-#[derive(Debug)]
+#[derive(PartialEq, Debug)]
 struct NextTokens {
     forward: BufferTokSet,
     reverse: BufferTokSet,
@@ -265,9 +479,9 @@ impl NextTokens {
     }
 }
 
-#[derive(Debug)]
+#[derive(PartialEq, Debug)]
 struct TokenPaths {
-    maps: HashMap<TokID, HashMap<TokID, NextTokens>>,
+    maps: HashMap<Prefix, NextTokens>,
 }
 
 impl TokenPaths {
@@ -277,26 +491,53 @@ impl TokenPaths {
         }
     }
 
-    fn append(&mut self, prefix: Prefix2, forward_value: TokID, reverse_value: TokID) {
-        let nested = self.maps.entry(prefix.0).or_insert_with(HashMap::new);
-        let toksets = nested.entry(prefix.1).or_insert_with(NextTokens::new);
+    fn append(&mut self, prefix: Prefix, forward_value: TokID, reverse_value: TokID) {
+        let toksets = self.maps.entry(prefix).or_insert_with(NextTokens::new);
         toksets.forward.add_entry(forward_value);
         toksets.reverse.add_entry(reverse_value);
     }
 
-    fn get(&self, prefix: Prefix2) -> Option<&NextTokens> {
-        self.maps
-            .get(&prefix.0)
-            .and_then(|nested| nested.get(&prefix.1))
+    fn get(&self, prefix: &[TokID]) -> Option<&NextTokens> {
+        self.maps.get(prefix)
     }
 
-    fn iterator(&self, direction: Direction, start: Prefix2) -> TokenIter {
+    fn iterator(&self, direction: Direction, start: Prefix, params: SamplingParams) -> TokenIter {
         TokenIter {
             paths: &self,
             direction,
             prefix: start,
             rng: thread_rng(),
+            params,
+        }
+    }
+
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u32(w, self.maps.len() as u32)?;
+        for (prefix, toksets) in &self.maps {
+            write_u32(w, prefix.len() as u32)?;
+            for tokid in prefix {
+                write_u32(w, *tokid)?;
+            }
+            toksets.forward.write_to(w)?;
+            toksets.reverse.write_to(w)?;
         }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<TokenPaths> {
+        let count = read_u32(r)?;
+        let mut maps = HashMap::with_capacity(count as usize);
+        for _ in 0..count {
+            let prefix_len = read_u32(r)? as usize;
+            let mut prefix = Vec::with_capacity(prefix_len);
+            for _ in 0..prefix_len {
+                prefix.push(read_u32(r)?);
+            }
+            let forward = BufferTokSet::read_from(r)?;
+            let reverse = BufferTokSet::read_from(r)?;
+            maps.insert(prefix, NextTokens { forward, reverse });
+        }
+        Ok(TokenPaths { maps })
     }
 }
 
@@ -304,7 +545,8 @@ struct TokenIter<'a> {
     paths: &'a TokenPaths,
     direction: Direction,
     rng: ThreadRng,
-    prefix: (TokID, TokID),
+    prefix: Prefix,
+    params: SamplingParams,
 }
 
 impl<'a> Iterator for TokenIter<'a> {
@@ -312,19 +554,25 @@ impl<'a> Iterator for TokenIter<'a> {
 
     fn next(&mut self) -> Option<TokID> {
         use Direction::{Forward, Reverse};
-        let toksets = self.paths.get(self.prefix)?;
+        let toksets = self.paths.get(&self.prefix)?;
 
         let m = match self.direction {
             Forward => &toksets.forward,
             Reverse => &toksets.reverse,
         };
 
-        let choice = m.choose(&mut self.rng);
+        let choice = m.choose_with(&mut self.rng, &self.params)?;
 
-        self.prefix = match self.direction {
-            Forward => (self.prefix.1, choice),
-            Reverse => (choice, self.prefix.0),
-        };
+        match self.direction {
+            Forward => {
+                self.prefix.remove(0);
+                self.prefix.push(choice);
+            }
+            Reverse => {
+                self.prefix.pop();
+                self.prefix.insert(0, choice);
+            }
+        }
 
         Some(choice)
     }
@@ -332,22 +580,38 @@ impl<'a> Iterator for TokenIter<'a> {
 
 type Entries = HashMap<TokID, BufferTokSet>;
 
-#[derive(Debug)]
+#[derive(PartialEq, Debug)]
 pub struct Chain {
+    order: usize,
     dict: Dict,
     paths: TokenPaths,
     entries: Entries,
 }
 
 impl Chain {
-    pub fn new() -> Chain {
+    /// Build a chain keyed by `order`-token prefixes. `order` must be at least 1.
+    pub fn with_order(order: usize) -> Chain {
+        assert!(order >= 1, "order must be at least 1");
         Chain {
+            order,
             paths: TokenPaths::new(),
             dict: Dict::new(),
             entries: HashMap::new(),
         }
     }
 
+    /// TokID of the `""` sentinel. Only valid once `feed` has been given a
+    /// non-empty token list; an empty list returns before inserting it.
+    fn none(&self) -> TokID {
+        self.dict
+            .get_tokid("")
+            .expect("sentinel token is inserted by feed")
+    }
+
+    fn entrypoint(&self) -> Prefix {
+        vec![self.none(); self.order]
+    }
+
     pub fn printsizes(&self) {
         println!(
             "Chain[dict: {}, paths: {}, entries: {}]",
@@ -357,24 +621,141 @@ impl Chain {
         );
     }
 
+    /// Serialize the whole trained model to `w` so it can be reloaded with
+    /// `load` instead of re-feeding the corpus.
+    pub fn save<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MODEL_MAGIC)?;
+        w.write_all(&[MODEL_VERSION])?;
+        write_u32(w, self.order as u32)?;
+        self.dict.write_to(w)?;
+        self.paths.write_to(w)?;
+        write_u32(w, self.entries.len() as u32)?;
+        for (tokid, set) in &self.entries {
+            write_u32(w, *tokid)?;
+            set.write_to(w)?;
+        }
+        Ok(())
+    }
+
+    /// Reconstruct a `Chain` previously written by `save`, without
+    /// recomputing any windows.
+    pub fn load<R: Read>(r: &mut R) -> io::Result<Chain> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MODEL_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a fake model file",
+            ));
+        }
+        let mut version = [0u8; 1];
+        r.read_exact(&mut version)?;
+        if version[0] != MODEL_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported model version {}", version[0]),
+            ));
+        }
+
+        let order = read_u32(r)? as usize;
+        let dict = Dict::read_from(r)?;
+        let paths = TokenPaths::read_from(r)?;
+
+        let entries_count = read_u32(r)?;
+        let mut entries = HashMap::with_capacity(entries_count as usize);
+        for _ in 0..entries_count {
+            let tokid = read_u32(r)?;
+            let set = BufferTokSet::read_from(r)?;
+            entries.insert(tokid, set);
+        }
+
+        Ok(Chain {
+            order,
+            dict,
+            paths,
+            entries,
+        })
+    }
+
+    /// Emit the order-N transition structure as Graphviz DOT. Each distinct
+    /// prefix becomes a node, and each forward successor becomes an edge
+    /// weighted by occurrence count.
+    pub fn to_dot<W: Write>(&self, w: &mut W, opts: &DotOptions) -> io::Result<()> {
+        let none = self.dict.get_tokid("");
+
+        writeln!(w, "digraph markov {{")?;
+        for (prefix, toksets) in &self.paths.maps {
+            if opts.skip_sentinel && prefix.iter().any(|t| Some(*t) == none) {
+                continue;
+            }
+
+            writeln!(
+                w,
+                "  {} [label=\"{}\"];",
+                dot_node_id(prefix),
+                escape_dot(&self.prefix_label(prefix))
+            )?;
+
+            let mut counts: HashMap<TokID, u32> = HashMap::new();
+            for i in 0..toksets.forward.length() {
+                *counts.entry(toksets.forward.get(i)).or_insert(0) += 1;
+            }
+
+            let mut edges: Vec<_> = counts.into_iter().collect();
+            edges.sort_by(|a, b| b.1.cmp(&a.1));
+            if let Some(top_n) = opts.top_n {
+                edges.truncate(top_n);
+            }
+
+            for (successor, count) in edges {
+                if opts.skip_sentinel && Some(successor) == none {
+                    continue;
+                }
+                let mut next_prefix = prefix[1..].to_vec();
+                next_prefix.push(successor);
+                writeln!(
+                    w,
+                    "  {} -> {} [label=\"{}\", penwidth={:.2}];",
+                    dot_node_id(prefix),
+                    dot_node_id(&next_prefix),
+                    count,
+                    1.0 + (count as f32).ln()
+                )?;
+            }
+        }
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+
+    fn prefix_label(&self, prefix: &[TokID]) -> String {
+        prefix
+            .iter()
+            .map(|t| self.dict.entry(*t).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     pub fn feed(&mut self, tokens: Vec<String>) -> &mut Chain {
         if tokens.is_empty() {
             return self;
         }
         let none = self.dict.tokid("");
-        let mut toks = vec![none, none, none];
+        // One extra sentinel up front (so the first window's reverse token
+        // is also "") plus `order` sentinels at each end to seed/terminate.
+        let mut toks = vec![none; self.order + 1];
         toks.extend(tokens.into_iter().map(|t| self.dict.tokid(&t)));
-        toks.push(none);
-        toks.push(none);
-        for p in toks.windows(4) {
-            if let [a, b, c, d] = *p {
-                let prefix = (b, c);
-                self.paths.append(prefix, d, a);
-
-                let eprefix: Prefix1 = b;
-                let etokset = self.entries.entry(eprefix).or_insert_with(TokSet::new);
-                etokset.add_entry(c);
-            }
+        toks.extend(vec![none; self.order]);
+
+        let window_size = self.order + 2;
+        for p in toks.windows(window_size) {
+            let reverse_value = p[0];
+            let prefix = p[1..=self.order].to_vec();
+            let forward_value = p[window_size - 1];
+            self.paths.append(prefix, forward_value, reverse_value);
+
+            let eprefix = p[1];
+            let etokset = self.entries.entry(eprefix).or_insert_with(TokSet::new);
+            etokset.add_entry(p[2]);
         }
         self
     }
@@ -397,26 +778,37 @@ impl Chain {
         Ok(self)
     }
 
-    pub fn generate_from_prefix(&mut self, dir: Direction, prefix: Prefix2) -> Vec<String> {
-        if self.paths.get(prefix).is_none() {
+    pub fn generate_from_prefix(
+        &self,
+        dir: Direction,
+        prefix: Prefix,
+        params: SamplingParams,
+    ) -> Vec<String> {
+        if self.paths.get(&prefix).is_none() {
             return vec![];
         }
 
-        let none = self.dict.tokid("");
+        let none = self.none();
 
         self.paths
-            .iterator(dir, prefix)
+            .iterator(dir, prefix, params)
+            .take(MAX_GENERATED_TOKENS)
             .take_while(|i| *i != none)
             .filter_map(|x| self.dict.entry(x))
             .collect()
     }
 
-    pub fn generate_one(&mut self) -> Option<Vec<String>> {
-        let none = self.dict.tokid("");
-        Some(self.generate_from_prefix(Direction::Forward, (none, none)))
+    pub fn generate_one(&self, params: SamplingParams) -> Option<Vec<String>> {
+        let prefix = self.entrypoint();
+        Some(self.generate_from_prefix(Direction::Forward, prefix, params))
     }
 
-    pub fn generate_one_from(&mut self, rng: &mut ThreadRng, start: &str) -> Option<Vec<String>> {
+    pub fn generate_one_from(
+        &self,
+        rng: &mut ThreadRng,
+        start: &str,
+        params: SamplingParams,
+    ) -> Option<Vec<String>> {
         let mut phrase = vec![];
         for word in start.split_whitespace() {
             let tokid = self.dict.get_tokid(&word.to_string())?;
@@ -425,24 +817,36 @@ impl Chain {
 
         match phrase.len() {
             0 => {
-                return self.generate_one();
+                return self.generate_one(params);
             }
             1 => {
                 // One-word phrases use entries to get the next
                 let possibles = self.entries.get(&phrase[0])?;
-                phrase.push(possibles.choose(rng));
+                phrase.push(possibles.choose_with(rng, &params)?);
             }
             _ => {
                 // TODO Ensure the phrase can be reconstructed
             }
         }
 
+        // Pad shorter seeds out to the full order with the "" sentinel.
+        let none = self.none();
+        while phrase.len() < self.order {
+            phrase.insert(0, none);
+        }
+
         let size = phrase.len();
-        let reverse_prefix = (phrase[0], phrase[1]);
-        let forward_prefix = (phrase[size - 2], phrase[size - 1]);
-        let end = self.generate_from_prefix(Direction::Forward, forward_prefix);
-        let mut begin = self.generate_from_prefix(Direction::Reverse, reverse_prefix);
-        let middle: Vec<_> = phrase.iter().filter_map(|x| self.dict.entry(*x)).collect();
+        let reverse_prefix = phrase[0..self.order].to_vec();
+        let forward_prefix = phrase[size - self.order..].to_vec();
+        let end = self.generate_from_prefix(Direction::Forward, forward_prefix, params);
+        let mut begin = self.generate_from_prefix(Direction::Reverse, reverse_prefix, params);
+        // Padding sentinels are only for the prefix lookups above; they must
+        // not leak into the output as empty words.
+        let middle: Vec<_> = phrase
+            .iter()
+            .filter(|x| **x != none)
+            .filter_map(|x| self.dict.entry(*x))
+            .collect();
         begin.reverse();
         begin.extend(middle);
         begin.extend(end);
@@ -459,16 +863,21 @@ impl Chain {
         }
     }
 
-    pub fn generate_best_from(&mut self, start: String, target_words: i32) -> Option<String> {
+    pub fn generate_best_from(
+        &self,
+        start: String,
+        target_words: i32,
+        params: SamplingParams,
+    ) -> Option<String> {
         let mut rng = thread_rng();
         let gens: Vec<_> = (1..50)
-            .map(|_| self.generate_one_from(&mut rng, &start[..]))
+            .map(|_| self.generate_one_from(&mut rng, &start[..], params))
             .collect();
         Self::choose_best(gens, target_words).map(|v| v.join(" "))
     }
 
-    pub fn generate_best(&mut self, target_words: i32) -> Option<String> {
-        let gens: Vec<_> = (1..50).map(|_| self.generate_one()).collect();
+    pub fn generate_best(&self, target_words: i32, params: SamplingParams) -> Option<String> {
+        let gens: Vec<_> = (1..50).map(|_| self.generate_one(params)).collect();
         Self::choose_best(gens, target_words).map(|v| v.join(" "))
     }
 }